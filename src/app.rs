@@ -1,11 +1,16 @@
 use std::time::Duration;
 
-use pug::clap::{App, Arg, SubCommand};
+use pug::clap::{App, Arg, ArgMatches, SubCommand};
 use serialport::{self, SerialPortSettings};
 
 use super::{
+    console,
     errors::Result,
-    tty::{publisher, BaudRate, DataBits, FlowControl, Parity, Pseudo, StopBits},
+    gui,
+    tty::{
+        publisher, BaudRate, DataBits, Encoding, FlowControl, Framing, Parity, Pseudo,
+        PublisherOptions, StopBits, Transcript,
+    },
 };
 
 pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
@@ -33,6 +38,92 @@ pub fn launch() -> Result<()> {
         .long("interval")
         .help("Interval in seconds")
         .takes_value(true);
+    let encoding = Arg::with_name("encoding")
+        .short("e")
+        .long("encoding")
+        .help("How to turn protocol file lines into bytes(text,hex,raw), defaults to guessing by extension")
+        .takes_value(true);
+    let transcript = Arg::with_name("transcript")
+        .long("transcript")
+        .help("Capture every sent/received chunk to this file with an RFC3339 timestamp")
+        .takes_value(true);
+    let name = Arg::with_name("name")
+        .required(true)
+        .short("n")
+        .long("name")
+        .help("Device name(/dev/serial0,/dev/ttyUSB0,/dev/pts/1,COM1)")
+        .takes_value(true);
+    let baud_rate_help = format!(
+        "The baud rate in symbols-per-second({})",
+        BaudRate
+            .clone()
+            .into_iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<String>>()
+            .join(",")
+    );
+    let baud_rate = Arg::with_name("baud_rate")
+        .short("B")
+        .long("baud-rate")
+        .help(&baud_rate_help)
+        .takes_value(true);
+    let data_bits_help = format!(
+        "Number of bits used to represent a character sent on the line({})",
+        DataBits
+            .keys()
+            .cloned()
+            .collect::<Vec<&'static str>>()
+            .join(",")
+    );
+    let data_bits = Arg::with_name("data_bits")
+        .short("D")
+        .long("data-bits")
+        .help(&data_bits_help)
+        .takes_value(true);
+    let flow_control_help = format!(
+        "The type of signalling to use for controlling data transfer({})",
+        FlowControl
+            .keys()
+            .cloned()
+            .collect::<Vec<&'static str>>()
+            .join(",")
+    );
+    let flow_control = Arg::with_name("flow_control")
+        .short("f")
+        .long("flow-control")
+        .help(&flow_control_help)
+        .takes_value(true);
+    let parity_help = format!(
+        "The type of parity to use for error checking({})",
+        Parity
+            .keys()
+            .cloned()
+            .collect::<Vec<&'static str>>()
+            .join(",")
+    );
+    let parity = Arg::with_name("parity")
+        .short("P")
+        .long("parity")
+        .help(&parity_help)
+        .takes_value(true);
+    let stop_bits_help = format!(
+        "Number of bits to use to signal the end of a character({})",
+        &StopBits
+            .keys()
+            .cloned()
+            .collect::<Vec<&'static str>>()
+            .join(",")
+    );
+    let stop_bits = Arg::with_name("stop_bits")
+        .short("s")
+        .long("stop-bits")
+        .help(&stop_bits_help)
+        .takes_value(true);
+    let timeout = Arg::with_name("timeout")
+        .short("t")
+        .long("timeout")
+        .help("Amount of time to wait to receive data before timing out")
+        .takes_value(true);
     let matches = App::new(NAME)
         .version(VERSION)
         .author(AUTHORS)
@@ -44,88 +135,63 @@ pub fn launch() -> Result<()> {
                 .about("Create a pseudo serial-port device")
                 .arg(&path)
                 .arg(&interval)
-                .arg(&delay),
+                .arg(&delay)
+                .arg(&encoding)
+                .arg(&transcript),
         ).subcommand(
             SubCommand::with_name("publisher")
                 .about("Publisher message to serial-port")
                 .arg(&path)
                 .arg(&interval)
                 .arg(&delay)
+                .arg(&encoding)
+                .arg(&transcript)
+                .arg(&name)
+                .arg(&baud_rate)
+                .arg(&data_bits)
+                .arg(&flow_control)
+                .arg(&parity)
+                .arg(&stop_bits)
+                .arg(&timeout)
                 .arg(
-                    Arg::with_name("name")
-                        .required(true)
-                        .short("n")
-                        .long("name")
-                        .help("Device name(/dev/serial0,/dev/ttyUSB0,/dev/pts/1,COM1)")
-                        .takes_value(true),
-                ).arg(
-                    Arg::with_name("baud_rate")
-                        .short("B")
-                        .long("baud-rate")
-                        .help(&format!(
-                            "The baud rate in symbols-per-second({})",
-                            BaudRate
-                                .clone()
-                                .into_iter()
-                                .map(|i| i.to_string())
-                                .collect::<Vec<String>>()
-                                .join(",")
-                        )).takes_value(true),
-                ).arg(
-                    Arg::with_name("data_bits")
-                        .short("D")
-                        .long("data-bits")
-                        .help(&format!(
-                            "Number of bits used to represent a character sent on the line({})",
-                            DataBits
-                                .keys()
-                                .cloned()
-                                .collect::<Vec<&'static str>>()
-                                .join(",")
-                        )).takes_value(true),
+                    Arg::with_name("full_duplex")
+                        .long("full-duplex")
+                        .help("Spawn a dedicated reader thread so sends and receives happen concurrently"),
                 ).arg(
-                    Arg::with_name("flow_control")
-                        .short("f")
-                        .long("flow-control")
-                        .help(&format!(
-                            "The type of signalling to use for controlling data transfer({})",
-                            FlowControl
-                                .keys()
-                                .cloned()
-                                .collect::<Vec<&'static str>>()
-                                .join(",")
-                        )).takes_value(true),
+                    Arg::with_name("framing")
+                        .long("framing")
+                        .help("Frame requests/replies so a response can be matched to its request(slip)")
+                        .takes_value(true),
                 ).arg(
-                    Arg::with_name("parity")
-                        .short("P")
-                        .long("parity")
-                        .help(&format!(
-                            "The type of parity to use for error checking({})",
-                            Parity
-                                .keys()
-                                .cloned()
-                                .collect::<Vec<&'static str>>()
-                                .join(",")
-                        )).takes_value(true),
+                    Arg::with_name("retries")
+                        .long("retries")
+                        .help("Number of times to retry a framed write after a response timeout")
+                        .takes_value(true),
                 ).arg(
-                    Arg::with_name("stop_bits")
-                        .short("s")
-                        .long("stop-bits")
-                        .help(&format!(
-                            "Number of bits to use to signal the end of a character({})",
-                            &StopBits
-                                .keys()
-                                .cloned()
-                                .collect::<Vec<&'static str>>()
-                                .join(",")
-                        )).takes_value(true),
+                    Arg::with_name("keepalive")
+                        .long("keepalive")
+                        .help("A heartbeat payload to send on its own timer while the protocol walk is idle")
+                        .takes_value(true),
                 ).arg(
-                    Arg::with_name("timeout")
-                        .short("t")
-                        .long("timeout")
-                        .help("Amount of time to wait to receive data before timing out")
+                    Arg::with_name("keepalive_interval")
+                        .long("keepalive-interval")
+                        .help("Interval between keepalive sends, in milliseconds")
                         .takes_value(true),
                 ),
+        ).subcommand(
+            SubCommand::with_name("console")
+                .about("Interactive REPL for stepping through a serial-port session")
+                .arg(&name)
+                .arg(&baud_rate)
+                .arg(&data_bits)
+                .arg(&flow_control)
+                .arg(&parity)
+                .arg(&stop_bits)
+                .arg(&timeout)
+                .arg(&encoding),
+        ).subcommand(
+            SubCommand::with_name("gui")
+                .about("Launch the imgui live serial monitor"),
         ).get_matches();
 
     if let Some(matches) = matches.subcommand_matches("pseudo") {
@@ -141,9 +207,14 @@ pub fn launch() -> Result<()> {
             Some(v) => Some(Duration::from_micros(v.parse::<u64>().unwrap())),
             None => None,
         };
+        let encoding = matches.value_of("encoding").and_then(Encoding::from_str);
+        let transcript = match matches.value_of("transcript") {
+            Some(v) => Some(Transcript::create(v)?),
+            None => None,
+        };
 
         let port = Pseudo::new()?;
-        return port.start(path, interval, delay);
+        return port.start(path, interval, delay, encoding, transcript);
     }
 
     if let Some(matches) = matches.subcommand_matches("publisher") {
@@ -161,41 +232,100 @@ pub fn launch() -> Result<()> {
             None => None,
         };
 
-        let mut settings = SerialPortSettings::default();
-        settings.baud_rate = matches
-            .value_of("baud_rate")
-            .unwrap_or("9600")
+        let settings = settings_from_matches(matches);
+
+        let full_duplex = matches.is_present("full_duplex");
+        let framing = Framing::from_str(matches.value_of("framing").unwrap_or("none"));
+        let retries = matches
+            .value_of("retries")
+            .unwrap_or("3")
             .parse::<u32>()
-            .unwrap()
-            .into();
-
-        settings.data_bits = match matches.value_of("data_bits").unwrap_or("Eight") {
-            "Five" => serialport::DataBits::Five,
-            "Six" => serialport::DataBits::Six,
-            "Seven" => serialport::DataBits::Seven,
-            "Eight" => serialport::DataBits::Eight,
-            _ => panic!("bad data bits"),
-        };
-        settings.flow_control = match matches.value_of("flow_control").unwrap_or("None") {
-            "None" => serialport::FlowControl::None,
-            "Software" => serialport::FlowControl::Software,
-            "Hardware" => serialport::FlowControl::Hardware,
-            _ => panic!("bad flow control"),
-        };
-        settings.parity = match matches.value_of("parity").unwrap_or("None") {
-            "None" => serialport::Parity::None,
-            "Odd" => serialport::Parity::Odd,
-            "Even" => serialport::Parity::Even,
-            _ => panic!("bad parity"),
-        };
-        settings.stop_bits = match matches.value_of("stop_bits").unwrap_or("One") {
-            "One" => serialport::StopBits::One,
-            "Two" => serialport::StopBits::Two,
-            _ => panic!("bad stop bits"),
+            .unwrap();
+        let encoding = matches.value_of("encoding").and_then(Encoding::from_str);
+        let transcript = match matches.value_of("transcript") {
+            Some(v) => Some(Transcript::create(v)?),
+            None => None,
         };
+        let keepalive = matches
+            .value_of("keepalive")
+            .map(|v| v.as_bytes().to_vec());
+        let keepalive_interval = Duration::from_millis(
+            matches
+                .value_of("keepalive_interval")
+                .unwrap_or("5000")
+                .parse::<u64>()
+                .unwrap(),
+        );
 
-        return publisher(path, &name.to_string(), &settings, interval, delay);
+        return publisher(
+            path,
+            &name.to_string(),
+            &settings,
+            interval,
+            delay,
+            PublisherOptions {
+                full_duplex,
+                framing,
+                retries,
+                encoding,
+                transcript,
+                keepalive,
+                keepalive_interval,
+            },
+        );
+    }
+
+    if let Some(matches) = matches.subcommand_matches("console") {
+        let name = matches.value_of("name").unwrap();
+        let settings = settings_from_matches(matches);
+        let encoding = matches.value_of("encoding").and_then(Encoding::from_str);
+
+        return console::run(&name.to_string(), &settings, encoding);
+    }
+
+    if matches.subcommand_matches("gui").is_some() {
+        return gui::run();
     }
 
     Ok(())
 }
+
+fn settings_from_matches(matches: &ArgMatches) -> SerialPortSettings {
+    let mut settings = SerialPortSettings::default();
+    settings.baud_rate = matches
+        .value_of("baud_rate")
+        .unwrap_or("9600")
+        .parse::<u32>()
+        .unwrap()
+        .into();
+
+    settings.data_bits = match matches.value_of("data_bits").unwrap_or("Eight") {
+        "Five" => serialport::DataBits::Five,
+        "Six" => serialport::DataBits::Six,
+        "Seven" => serialport::DataBits::Seven,
+        "Eight" => serialport::DataBits::Eight,
+        _ => panic!("bad data bits"),
+    };
+    settings.flow_control = match matches.value_of("flow_control").unwrap_or("None") {
+        "None" => serialport::FlowControl::None,
+        "Software" => serialport::FlowControl::Software,
+        "Hardware" => serialport::FlowControl::Hardware,
+        _ => panic!("bad flow control"),
+    };
+    settings.parity = match matches.value_of("parity").unwrap_or("None") {
+        "None" => serialport::Parity::None,
+        "Odd" => serialport::Parity::Odd,
+        "Even" => serialport::Parity::Even,
+        _ => panic!("bad parity"),
+    };
+    settings.stop_bits = match matches.value_of("stop_bits").unwrap_or("One") {
+        "One" => serialport::StopBits::One,
+        "Two" => serialport::StopBits::Two,
+        _ => panic!("bad stop bits"),
+    };
+    if let Some(v) = matches.value_of("timeout") {
+        settings.timeout = Duration::from_millis(v.parse::<u64>().unwrap());
+    }
+
+    settings
+}
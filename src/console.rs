@@ -0,0 +1,177 @@
+use std::fs::File;
+use std::io::{self, prelude::*, BufReader};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serialport::{self, SerialPortSettings};
+
+use super::{
+    errors::Result,
+    tty::{self, format_bytes, parse_hex_line, spawn_reader, Encoding},
+};
+
+type Port = Arc<Mutex<Box<serialport::SerialPort>>>;
+
+struct Protocol {
+    lines: Vec<Vec<u8>>,
+    cursor: usize,
+}
+
+fn check_repeat_arg(rest: &str) -> usize {
+    match rest.split_whitespace().next() {
+        Some(n) => n.parse::<usize>().unwrap_or(1),
+        None => 1,
+    }
+}
+
+fn load_lines(path: &str, encoding: Option<Encoding>) -> Result<Vec<Vec<u8>>> {
+    let encoding = Encoding::for_path(path.as_ref(), encoding);
+    let fd = File::open(path)?;
+    let br = BufReader::new(fd);
+    let mut lines = Vec::new();
+    for line in br.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        lines.push(match encoding {
+            Encoding::Hex => parse_hex_line(line)?,
+            _ => line.as_bytes().to_vec(),
+        });
+    }
+    Ok(lines)
+}
+
+fn send(port: &Port, bytes: &[u8]) -> Result<()> {
+    let mut port = port.lock().unwrap();
+    let len = port.write(bytes)?;
+    info!("send {} bytes: {}", len, format_bytes(bytes));
+    Ok(())
+}
+
+fn step(port: &Port, protocol: &mut Option<Protocol>) -> Result<()> {
+    match protocol {
+        Some(protocol) => {
+            if protocol.cursor >= protocol.lines.len() {
+                info!("protocol exhausted");
+                return Ok(());
+            }
+            let line = protocol.lines[protocol.cursor].clone();
+            protocol.cursor += 1;
+            send(port, &line)
+        }
+        None => {
+            error!("no protocol loaded, use `load <path>` first");
+            Ok(())
+        }
+    }
+}
+
+fn execute(
+    cmd: &str,
+    rest: &str,
+    port: &Port,
+    protocol: &mut Option<Protocol>,
+    last_received: &Arc<Mutex<Vec<u8>>>,
+    encoding: Option<Encoding>,
+) -> Result<()> {
+    match cmd {
+        "send" => send(port, rest.as_bytes()),
+        "hex" => send(port, &parse_hex_line(rest)?),
+        "load" => {
+            let lines = load_lines(rest, encoding)?;
+            info!("loaded {} lines from {}", lines.len(), rest);
+            *protocol = Some(Protocol { lines, cursor: 0 });
+            Ok(())
+        }
+        "step" => step(port, protocol),
+        "dump" => {
+            let buf = last_received.lock().unwrap();
+            info!("last received {} bytes: {}", buf.len(), format_bytes(&buf));
+            Ok(())
+        }
+        "" => Ok(()),
+        _ => {
+            error!("unknown command: {}", cmd);
+            Ok(())
+        }
+    }
+}
+
+pub fn run(name: &String, settings: &SerialPortSettings, encoding: Option<Encoding>) -> Result<()> {
+    let port = tty::open_serial_port(name, settings)?;
+    let port: Port = Arc::new(Mutex::new(port));
+
+    let reader = spawn_reader(port.lock().unwrap().try_clone()?, None);
+    let last_received: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+    {
+        let last_received = last_received.clone();
+        thread::spawn(move || {
+            while let Ok((_, chunk)) = reader.rx.recv() {
+                *last_received.lock().unwrap() = chunk;
+            }
+        });
+    }
+
+    let mut protocol: Option<Protocol> = None;
+    let mut last_command: Option<String> = None;
+
+    let stdin = io::stdin();
+    loop {
+        print!("equuleus> ");
+        io::stdout().flush()?;
+
+        let mut raw = String::new();
+        if stdin.read_line(&mut raw)? == 0 {
+            break;
+        }
+        let raw = raw.trim();
+
+        let line = if raw.is_empty() {
+            match &last_command {
+                Some(l) => l.clone(),
+                None => continue,
+            }
+        } else {
+            raw.to_string()
+        };
+
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let cmd = parts.next().unwrap_or("");
+        let rest = parts.next().unwrap_or("").trim();
+
+        if cmd == "quit" || cmd == "exit" {
+            break;
+        }
+
+        if cmd == "repeat" {
+            let n = check_repeat_arg(rest);
+            match last_command.clone() {
+                Some(prev) => {
+                    let mut prev_parts = prev.splitn(2, char::is_whitespace);
+                    let prev_cmd = prev_parts.next().unwrap_or("");
+                    let prev_rest = prev_parts.next().unwrap_or("").trim();
+                    for i in 0..n {
+                        info!("repeat {}/{}: {}", i + 1, n, prev);
+                        execute(
+                            prev_cmd,
+                            prev_rest,
+                            &port,
+                            &mut protocol,
+                            &last_received,
+                            encoding,
+                        )?;
+                    }
+                }
+                None => error!("nothing to repeat"),
+            }
+            continue;
+        }
+
+        execute(cmd, rest, &port, &mut protocol, &last_received, encoding)?;
+        last_command = Some(line);
+    }
+
+    Ok(())
+}
@@ -3,7 +3,10 @@ error_chain!{
         StdIo(std::io::Error);
         StdStrUtf8(std::str::Utf8Error);
         StdStringFromUtf8(std::string::FromUtf8Error);
+        StdNumParseInt(std::num::ParseIntError);
 
         SerialPort(serialport::Error);
+        GliumDisplayCreation(glium::backend::glutin::DisplayCreationError);
+        GliumSwapBuffers(glium::SwapBuffersError);
     }
 }
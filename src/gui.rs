@@ -0,0 +1,334 @@
+use std::path::Path;
+use std::sync::mpsc::TryRecvError;
+use std::time::{Duration, Instant};
+
+use glium::glutin::{self, Event, WindowEvent};
+use glium::{Display, Surface};
+use imgui::{ImGuiCond, ImString, Ui};
+use imgui_glium_renderer::Renderer;
+use serialport::{self, SerialPortSettings};
+
+use super::{
+    errors::Result,
+    tty::{
+        self, hex_dump, parse_hex_line, spawn_reader, BaudRate, DataBits, Encoding, FlowControl,
+        Parity, StopBits,
+    },
+};
+
+struct Entry {
+    direction: &'static str,
+    bytes: Vec<u8>,
+}
+
+impl Entry {
+    fn line(&self) -> String {
+        format!("{} {}", self.direction, hex_dump(&self.bytes))
+    }
+}
+
+struct Settings {
+    name: ImString,
+    baud_rate: i32,
+    data_bits: i32,
+    flow_control: i32,
+    parity: i32,
+    stop_bits: i32,
+}
+
+impl Settings {
+    fn new() -> Self {
+        Settings {
+            name: ImString::new(""),
+            baud_rate: BaudRate.iter().position(|&b| b == 9600).unwrap_or(0) as i32,
+            data_bits: DataBits.keys().position(|&k| k == "Eight").unwrap_or(0) as i32,
+            flow_control: FlowControl.keys().position(|&k| k == "None").unwrap_or(0) as i32,
+            parity: Parity.keys().position(|&k| k == "None").unwrap_or(0) as i32,
+            stop_bits: StopBits.keys().position(|&k| k == "One").unwrap_or(0) as i32,
+        }
+    }
+
+    fn to_serial_port_settings(&self) -> SerialPortSettings {
+        let mut settings = SerialPortSettings::default();
+        settings.baud_rate = BaudRate[self.baud_rate as usize].into();
+        settings.data_bits = match *DataBits.keys().nth(self.data_bits as usize).unwrap() {
+            "Five" => serialport::DataBits::Five,
+            "Six" => serialport::DataBits::Six,
+            "Seven" => serialport::DataBits::Seven,
+            _ => serialport::DataBits::Eight,
+        };
+        settings.flow_control = match *FlowControl.keys().nth(self.flow_control as usize).unwrap() {
+            "Software" => serialport::FlowControl::Software,
+            "Hardware" => serialport::FlowControl::Hardware,
+            _ => serialport::FlowControl::None,
+        };
+        settings.parity = match *Parity.keys().nth(self.parity as usize).unwrap() {
+            "Odd" => serialport::Parity::Odd,
+            "Even" => serialport::Parity::Even,
+            _ => serialport::Parity::None,
+        };
+        settings.stop_bits = match *StopBits.keys().nth(self.stop_bits as usize).unwrap() {
+            "Two" => serialport::StopBits::Two,
+            _ => serialport::StopBits::One,
+        };
+        settings
+    }
+}
+
+fn combo_labels(keys: impl Iterator<Item = &'static &'static str>) -> Vec<ImString> {
+    keys.map(|k| ImString::new(*k)).collect()
+}
+
+struct Monitor {
+    port: Option<Box<serialport::SerialPort>>,
+    reader: Option<tty::Reader>,
+    log: Vec<Entry>,
+    input: ImString,
+    protocol_path: ImString,
+    error: Option<String>,
+}
+
+impl Monitor {
+    fn new() -> Self {
+        Monitor {
+            port: None,
+            reader: None,
+            log: Vec::new(),
+            input: ImString::with_capacity(256),
+            protocol_path: ImString::new(""),
+            error: None,
+        }
+    }
+
+    fn connect(&mut self, name: &str, settings: &SerialPortSettings) {
+        match tty::open_serial_port(&name.to_string(), settings) {
+            Ok(port) => match port.try_clone() {
+                Ok(clone) => {
+                    self.reader = Some(spawn_reader(clone, None));
+                    self.port = Some(port);
+                    self.error = None;
+                }
+                Err(e) => self.error = Some(format!("{:?}", e)),
+            },
+            Err(e) => self.error = Some(format!("{:?}", e)),
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.port = None;
+        self.reader = None;
+    }
+
+    fn poll_received(&mut self) {
+        if let Some(reader) = &self.reader {
+            loop {
+                match reader.rx.try_recv() {
+                    Ok((_, bytes)) => self.log.push(Entry { direction: "rx", bytes }),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            }
+        }
+    }
+
+    fn send(&mut self, bytes: Vec<u8>) {
+        if bytes.is_empty() {
+            return;
+        }
+        if let Some(port) = &mut self.port {
+            match port.write(&bytes) {
+                Ok(len) => self.log.push(Entry {
+                    direction: "tx",
+                    bytes: bytes[..len].to_vec(),
+                }),
+                Err(e) => self.error = Some(format!("{:?}", e)),
+            }
+        } else {
+            self.error = Some("not connected".to_string());
+        }
+    }
+
+    fn replay(&mut self, path: &str) {
+        let path = Path::new(path);
+        if !path.is_dir() {
+            self.error = Some(format!("{} is not a directory", path.display()));
+            return;
+        }
+        match path.read_dir() {
+            Ok(entries) => {
+                for entry in entries.filter_map(|e| e.ok()) {
+                    let file = entry.path();
+                    let encoding = Encoding::for_path(&file, None);
+                    match std::fs::read_to_string(&file) {
+                        Ok(contents) => {
+                            for line in contents.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                                let bytes = match encoding {
+                                    Encoding::Hex => parse_hex_line(line).unwrap_or_default(),
+                                    _ => line.as_bytes().to_vec(),
+                                };
+                                self.send(bytes);
+                            }
+                        }
+                        Err(e) => self.error = Some(format!("{:?}", e)),
+                    }
+                }
+            }
+            Err(e) => self.error = Some(format!("{:?}", e)),
+        }
+    }
+}
+
+fn draw(ui: &Ui, monitor: &mut Monitor, settings: &mut Settings, connected: bool) {
+    ui.window(im_str!("equuleus"))
+        .size((760.0, 560.0), ImGuiCond::FirstUseEver)
+        .build(|| {
+            ui.input_text(im_str!("device"), &mut settings.name).build();
+            ui.combo(
+                im_str!("baud rate"),
+                &mut settings.baud_rate,
+                &BaudRate
+                    .iter()
+                    .map(|b| ImString::new(b.to_string()))
+                    .collect::<Vec<_>>()
+                    .iter()
+                    .map(|s| s.as_ref())
+                    .collect::<Vec<_>>(),
+                8,
+            );
+            ui.combo(
+                im_str!("data bits"),
+                &mut settings.data_bits,
+                &combo_labels(DataBits.keys())
+                    .iter()
+                    .map(|s| s.as_ref())
+                    .collect::<Vec<_>>(),
+                4,
+            );
+            ui.combo(
+                im_str!("flow control"),
+                &mut settings.flow_control,
+                &combo_labels(FlowControl.keys())
+                    .iter()
+                    .map(|s| s.as_ref())
+                    .collect::<Vec<_>>(),
+                3,
+            );
+            ui.combo(
+                im_str!("parity"),
+                &mut settings.parity,
+                &combo_labels(Parity.keys())
+                    .iter()
+                    .map(|s| s.as_ref())
+                    .collect::<Vec<_>>(),
+                3,
+            );
+            ui.combo(
+                im_str!("stop bits"),
+                &mut settings.stop_bits,
+                &combo_labels(StopBits.keys())
+                    .iter()
+                    .map(|s| s.as_ref())
+                    .collect::<Vec<_>>(),
+                2,
+            );
+            ui.same_line(0.0);
+            if !connected {
+                if ui.small_button(im_str!("connect")) {
+                    let name = settings.name.to_str().to_string();
+                    let serial_settings = settings.to_serial_port_settings();
+                    monitor.connect(&name, &serial_settings);
+                }
+            } else if ui.small_button(im_str!("disconnect")) {
+                monitor.disconnect();
+            }
+
+            ui.separator();
+
+            ui.child_frame(im_str!("log"), (0.0, 380.0))
+                .show_borders(true)
+                .build(|| {
+                    for entry in &monitor.log {
+                        ui.text(entry.line());
+                    }
+                });
+
+            ui.separator();
+
+            ui.input_text(im_str!("payload"), &mut monitor.input).build();
+            ui.same_line(0.0);
+            if ui.small_button(im_str!("send")) {
+                let text = monitor.input.to_str().to_string();
+                let bytes = parse_hex_line(&text).unwrap_or_else(|_| text.into_bytes());
+                monitor.send(bytes);
+            }
+
+            ui.input_text(im_str!("protocol dir"), &mut monitor.protocol_path)
+                .build();
+            ui.same_line(0.0);
+            if ui.small_button(im_str!("replay")) {
+                let path = monitor.protocol_path.to_str().to_string();
+                monitor.replay(&path);
+            }
+
+            if let Some(ref err) = monitor.error {
+                ui.text_colored((1.0, 0.3, 0.3, 1.0), im_str!("{}", err));
+            }
+        });
+}
+
+pub fn run() -> Result<()> {
+    let mut events_loop = glutin::EventsLoop::new();
+    let window = glutin::WindowBuilder::new()
+        .with_title("equuleus")
+        .with_dimensions((800, 600).into());
+    let context = glutin::ContextBuilder::new().with_vsync(true);
+    let display = Display::new(window, context, &events_loop)?;
+
+    let mut imgui = imgui::ImGui::init();
+    let mut renderer = match Renderer::init(&mut imgui, &display) {
+        Ok(r) => r,
+        Err(e) => bail!("failed to initialize imgui renderer: {}", e),
+    };
+
+    let mut monitor = Monitor::new();
+    let mut settings = Settings::new();
+    let mut last_frame = Instant::now();
+    let mut closed = false;
+
+    while !closed {
+        events_loop.poll_events(|event| {
+            if let Event::WindowEvent { event: WindowEvent::CloseRequested, .. } = event {
+                closed = true;
+            }
+        });
+
+        monitor.poll_received();
+
+        let now = Instant::now();
+        let delta = now.duration_since(last_frame);
+        last_frame = now;
+
+        let size = display
+            .gl_window()
+            .get_inner_size()
+            .unwrap_or_else(|| (800, 600).into());
+        let ui = imgui.frame(
+            imgui::FrameSize::new(size.width, size.height, 1.0),
+            delta.as_secs() as f32 + delta.subsec_nanos() as f32 / 1_000_000_000.0,
+        );
+
+        let connected = monitor.port.is_some();
+        draw(&ui, &mut monitor, &mut settings, connected);
+
+        let mut target = display.draw();
+        target.clear_color(0.1, 0.1, 0.1, 1.0);
+        if let Err(e) = renderer.render(&mut target, ui) {
+            bail!("failed to render frame: {}", e);
+        }
+        target.finish()?;
+
+        std::thread::sleep(Duration::from_millis(8));
+    }
+
+    Ok(())
+}
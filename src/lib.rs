@@ -7,10 +7,14 @@ pub extern crate lazy_static;
 
 extern crate chrono;
 extern crate clap;
+extern crate glium;
+#[macro_use]
 extern crate imgui;
+extern crate imgui_glium_renderer;
 extern crate serialport;
 
 pub mod app;
+pub mod console;
 pub mod errors;
 pub mod gui;
 pub mod tty;
@@ -1,12 +1,16 @@
+use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::fs::{self, DirEntry, File};
 use std::io::{prelude::*, BufReader};
 use std::os::unix::prelude::*;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
+use chrono::Utc;
 use serialport::{open_with_settings, posix::TTYPort, SerialPort, SerialPortSettings};
 
 use super::errors::Result;
@@ -43,6 +47,281 @@ lazy_static! {
     };
 }
 
+pub type Chunk = (SystemTime, Vec<u8>);
+
+pub struct Transcript {
+    file: File,
+}
+
+pub type SharedTranscript = Arc<Mutex<Transcript>>;
+
+impl Transcript {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<SharedTranscript> {
+        let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Arc::new(Mutex::new(Self { file })))
+    }
+
+    pub fn record(&mut self, direction: &str, bytes: &[u8]) -> Result<()> {
+        writeln!(
+            self.file,
+            "{} {} {} bytes: {}",
+            Utc::now().to_rfc3339(),
+            direction,
+            bytes.len(),
+            hex_dump(bytes)
+        )?;
+        Ok(())
+    }
+}
+
+// A reader thread that continuously drains a cloned port into a channel for
+// as long as the session runs, instead of the serialized
+// write-then-sleep-then-read cycle the rest of this module uses.
+pub struct Reader {
+    pub rx: Receiver<Chunk>,
+    pub handle: thread::JoinHandle<()>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Reader {
+    // Signal the thread to stop at its next loop iteration and wait for it,
+    // which takes until its current `port.read()` returns (at the latest
+    // after the port's timeout); without the signal it keeps retrying timed
+    // out reads for as long as the device stays silent.
+    pub fn stop_and_join(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = self.handle.join();
+    }
+}
+
+// Spawn a dedicated reader thread over its own handle to the port. The
+// thread keeps running until its stop flag is set (`stop_and_join`), the
+// receiving end of its channel is dropped (noticed on the next chunk), or
+// the port errors out (other than a read timeout, which is expected and
+// simply retried), logging every chunk it receives as it arrives rather than on a fixed
+// `delay`.
+pub fn spawn_reader(mut port: Box<SerialPort>, transcript: Option<SharedTranscript>) -> Reader {
+    let (tx, rx) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_thread = stop.clone();
+    let handle = thread::spawn(move || loop {
+        if stop_thread.load(Ordering::SeqCst) {
+            break;
+        }
+        let mut buf: Vec<u8> = vec![0; 1 << 10];
+        match port.read(buf.as_mut_slice()) {
+            Ok(0) => continue,
+            Ok(len) => {
+                let now = SystemTime::now();
+                let chunk = buf[..len].to_vec();
+                info!("receive {} bytes: {}", len, format_bytes(&chunk));
+                if let Some(ref transcript) = transcript {
+                    if let Err(e) = transcript.lock().unwrap().record("RX", &chunk) {
+                        error!("failed to write transcript: {:?}", e);
+                    }
+                }
+                if tx.send((now, chunk)).is_err() {
+                    break;
+                }
+            }
+            Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+            Err(e) => {
+                error!("reader thread stopped: {:?}", e);
+                break;
+            }
+        }
+    });
+    Reader { rx, handle, stop }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    None,
+    Slip,
+}
+
+impl Framing {
+    pub fn from_str(s: &str) -> Framing {
+        match s {
+            "slip" => Framing::Slip,
+            _ => Framing::None,
+        }
+    }
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Framing::None
+    }
+}
+
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+pub fn slip_encode(payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 2);
+    out.push(SLIP_END);
+    for &b in payload {
+        match b {
+            SLIP_END => {
+                out.push(SLIP_ESC);
+                out.push(SLIP_ESC_END);
+            }
+            SLIP_ESC => {
+                out.push(SLIP_ESC);
+                out.push(SLIP_ESC_ESC);
+            }
+            _ => out.push(b),
+        }
+    }
+    out.push(SLIP_END);
+    out
+}
+
+pub fn slip_decode(frame: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(frame.len());
+    let mut escaped = false;
+    for &b in frame {
+        if escaped {
+            out.push(match b {
+                SLIP_ESC_END => SLIP_END,
+                SLIP_ESC_ESC => SLIP_ESC,
+                other => other,
+            });
+            escaped = false;
+        } else if b == SLIP_ESC {
+            escaped = true;
+        } else {
+            out.push(b);
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Text,
+    Hex,
+    Raw,
+}
+
+impl Encoding {
+    pub fn from_str(s: &str) -> Option<Encoding> {
+        match s {
+            "text" => Some(Encoding::Text),
+            "hex" => Some(Encoding::Hex),
+            "raw" => Some(Encoding::Raw),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn for_path(path: &Path, over: Option<Encoding>) -> Encoding {
+        if let Some(e) = over {
+            return e;
+        }
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("hex") => Encoding::Hex,
+            Some("bin") => Encoding::Raw,
+            _ => Encoding::Text,
+        }
+    }
+}
+
+fn split_hex_token(token: &str) -> Vec<&str> {
+    if !token.contains("\\x") && !token.contains("0x") {
+        return vec![token];
+    }
+    let mut parts = Vec::new();
+    let mut rest = token;
+    while !rest.is_empty() {
+        rest = rest
+            .strip_prefix("\\x")
+            .or_else(|| rest.strip_prefix("0x"))
+            .unwrap_or(rest);
+        match rest.find("\\x").into_iter().chain(rest.find("0x")).min() {
+            Some(pos) => {
+                parts.push(&rest[..pos]);
+                rest = &rest[pos..];
+            }
+            None => {
+                parts.push(rest);
+                rest = "";
+            }
+        }
+    }
+    parts
+}
+
+pub fn parse_hex_line(line: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    for token in line.split_whitespace() {
+        for part in split_hex_token(token) {
+            out.push(u8::from_str_radix(part, 16)?);
+        }
+    }
+    Ok(out)
+}
+
+pub fn format_bytes(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_string(),
+        Err(_) => hex_dump(bytes),
+    }
+}
+
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let ascii: String = bytes
+        .iter()
+        .map(|&b| if b >= 0x20 && b < 0x7f { b as char } else { '.' })
+        .collect();
+    format!("{} | {}", hex.join(" "), ascii)
+}
+
+pub struct FrameReader {
+    rx: Receiver<Chunk>,
+    buf: Vec<u8>,
+}
+
+impl FrameReader {
+    pub fn new(rx: Receiver<Chunk>) -> Self {
+        Self { rx, buf: Vec::new() }
+    }
+
+    pub fn next_frame(&mut self, timeout: Duration) -> Result<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(pos) = self.buf.iter().position(|&b| b == SLIP_END) {
+                if pos == 0 {
+                    // Leading delimiter of the next frame; drop it and keep looking.
+                    self.buf.remove(0);
+                    continue;
+                }
+                let frame: Vec<u8> = self.buf.drain(..=pos).collect();
+                return Ok(slip_decode(&frame[..frame.len() - 1]));
+            }
+
+            let remaining = deadline.checked_duration_since(Instant::now());
+            let remaining = match remaining {
+                Some(d) if d > Duration::from_millis(0) => d,
+                _ => bail!("timed out waiting for a framed response"),
+            };
+            match self.rx.recv_timeout(remaining) {
+                Ok((_, chunk)) => self.buf.extend(chunk),
+                Err(RecvTimeoutError::Timeout) => bail!("timed out waiting for a framed response"),
+                Err(RecvTimeoutError::Disconnected) => bail!("reader thread disconnected"),
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        while self.rx.try_recv().is_ok() {}
+    }
+}
+
 pub struct Pseudo {
     master: Arc<Mutex<TTYPort>>,
     slave: TTYPort,
@@ -81,23 +360,27 @@ impl Pseudo {
         path: P,
         interval: Duration,
         delay: Option<Duration>,
+        encoding: Option<Encoding>,
+        transcript: Option<SharedTranscript>,
     ) -> Result<()> {
-        let cb = |it: &str| -> Result<()> {
+        let cb = |it: &[u8]| -> Result<()> {
             let port = self.master.clone();
             match port.lock() {
                 Ok(mut port) => {
                     if let Some(delay) = delay {
                         let mut buf: Vec<u8> = vec![0; 1 << 10];
                         let len = port.read(buf.as_mut_slice())?;
-                        info!(
-                            "receive {} bytes: {}",
-                            len,
-                            std::str::from_utf8(&buf[..len])?
-                        );
+                        info!("receive {} bytes: {}", len, format_bytes(&buf[..len]));
+                        if let Some(ref transcript) = transcript {
+                            transcript.lock().unwrap().record("RX", &buf[..len])?;
+                        }
                         thread::sleep(delay);
                     }
-                    let len = port.write(it.as_bytes())?;
-                    info!("send {} bytes: {}", len, it);
+                    let len = port.write(it)?;
+                    info!("send {} bytes: {}", len, format_bytes(it));
+                    if let Some(ref transcript) = transcript {
+                        transcript.lock().unwrap().record("TX", it)?;
+                    }
                 }
                 Err(e) => {
                     error!("failed in get serial port: {:?}", e);
@@ -107,37 +390,162 @@ impl Pseudo {
             thread::sleep(interval);
             Ok(())
         };
-        protocols(path.as_ref(), &cb)?;
+        protocols(path.as_ref(), encoding, &cb)?;
 
         Ok(())
     }
 }
 
+#[derive(Default)]
+pub struct PublisherOptions {
+    pub full_duplex: bool,
+    pub framing: Framing,
+    pub retries: u32,
+    pub encoding: Option<Encoding>,
+    pub transcript: Option<SharedTranscript>,
+    pub keepalive: Option<Vec<u8>>,
+    pub keepalive_interval: Duration,
+}
+
 pub fn publisher<P: AsRef<Path>>(
     path: P,
     name: &String,
     settings: &SerialPortSettings,
     interval: Duration,
     delay: Option<Duration>,
+    options: PublisherOptions,
 ) -> Result<()> {
+    let PublisherOptions {
+        full_duplex,
+        framing,
+        retries,
+        encoding,
+        transcript,
+        keepalive,
+        keepalive_interval,
+    } = options;
+    let timeout = settings.timeout;
     let port = open_serial_port(name, settings)?;
     let port = Arc::new(Mutex::new(port));
-    let cb = |it: &str| -> Result<()> {
+
+    // Framed replies are matched against requests off the same reader
+    // thread full-duplex mode uses, so either flag is enough to need one.
+    let mut join_handle = None;
+    let mut stop = None;
+    let mut rx_unused = None;
+    let frames = RefCell::new(None);
+    if full_duplex || framing != Framing::None {
+        let clone = port.lock().unwrap().try_clone()?;
+        let reader = spawn_reader(clone, transcript.clone());
+        join_handle = Some(reader.handle);
+        stop = Some(reader.stop);
+        if framing == Framing::Slip {
+            *frames.borrow_mut() = Some(FrameReader::new(reader.rx));
+        } else {
+            rx_unused = Some(reader.rx);
+        }
+    }
+
+    // A second thread keeps a tester-present style heartbeat going on its
+    // own timer, independent of the protocol walk below, so a device that
+    // closes its session after an inactivity timeout stays alive.
+    if let Some(payload) = keepalive {
+        let port = port.clone();
+        let transcript = transcript.clone();
+        thread::spawn(move || loop {
+            thread::sleep(keepalive_interval);
+            match port.lock() {
+                Ok(mut port) => match port.write(&payload) {
+                    Ok(len) => {
+                        info!("keepalive: send {} bytes: {}", len, format_bytes(&payload));
+                        if let Some(ref transcript) = transcript {
+                            if let Err(e) = transcript.lock().unwrap().record("TX(keepalive)", &payload) {
+                                error!("failed to write transcript: {:?}", e);
+                            }
+                        }
+                    }
+                    Err(e) => error!("keepalive write failed: {:?}", e),
+                },
+                Err(e) => error!("failed in get serial port: {:?}", e),
+            }
+        });
+    }
+
+    let cb = |it: &[u8]| -> Result<()> {
         thread::sleep(interval);
         let port = port.clone();
         match port.lock() {
             Ok(mut port) => {
-                let len = port.write(it.as_bytes())?;
-                info!("send {} bytes: {}", len, it);
-                if let Some(delay) = delay {
-                    thread::sleep(delay);
-                    let mut buf: Vec<u8> = vec![0; 1 << 10];
-                    let len = port.read(buf.as_mut_slice())?;
-                    info!(
-                        "receive {} bytes: {}",
-                        len,
-                        std::str::from_utf8(&buf[..len])?
-                    );
+                let request = match framing {
+                    Framing::Slip => slip_encode(it),
+                    Framing::None => it.to_vec(),
+                };
+
+                if framing == Framing::Slip {
+                    let mut attempt = 0;
+                    loop {
+                        let len = port.write(&request)?;
+                        info!("send {} bytes (framed): {}", len, format_bytes(it));
+                        if let Some(ref transcript) = transcript {
+                            transcript.lock().unwrap().record("TX", it)?;
+                        }
+                        let result = frames
+                            .borrow_mut()
+                            .as_mut()
+                            .expect("frame reader set up for slip framing")
+                            .next_frame(timeout);
+                        match result {
+                            Ok(response) => {
+                                info!(
+                                    "matched request {} with response {}",
+                                    format_bytes(it),
+                                    format_bytes(&response)
+                                );
+                                break;
+                            }
+                            Err(e) => {
+                                if attempt >= retries {
+                                    return Err(e);
+                                }
+                                attempt += 1;
+                                warn!(
+                                    "no framed response for {}, retrying ({}/{})",
+                                    format_bytes(it),
+                                    attempt,
+                                    retries
+                                );
+                                // Drop whatever of a reply to the attempt we just
+                                // gave up on has already arrived, rather than hand
+                                // it back as the retry's answer; a late reply that
+                                // lands after this point is still taken as the
+                                // retry's answer.
+                                frames
+                                    .borrow_mut()
+                                    .as_mut()
+                                    .expect("frame reader set up for slip framing")
+                                    .reset();
+                            }
+                        }
+                    }
+                } else {
+                    let len = port.write(&request)?;
+                    info!("send {} bytes: {}", len, format_bytes(it));
+                    if let Some(ref transcript) = transcript {
+                        transcript.lock().unwrap().record("TX", it)?;
+                    }
+                    // In full-duplex mode the reader thread owns receiving, so
+                    // there is nothing left to do here but write.
+                    if !full_duplex {
+                        if let Some(delay) = delay {
+                            thread::sleep(delay);
+                            let mut buf: Vec<u8> = vec![0; 1 << 10];
+                            let len = port.read(buf.as_mut_slice())?;
+                            info!("receive {} bytes: {}", len, format_bytes(&buf[..len]));
+                            if let Some(ref transcript) = transcript {
+                                transcript.lock().unwrap().record("RX", &buf[..len])?;
+                            }
+                        }
+                    }
                 }
             }
             Err(e) => {
@@ -147,12 +555,24 @@ pub fn publisher<P: AsRef<Path>>(
 
         Ok(())
     };
-    protocols(path.as_ref(), &cb)?;
+    protocols(path.as_ref(), encoding, &cb)?;
+
+    // The reader thread only notices its channel is gone on its next
+    // successful read, which may never come once the device falls silent;
+    // tell it to stop explicitly instead of blocking on join() forever.
+    drop(frames);
+    drop(rx_unused);
+    if let Some(stop) = stop {
+        stop.store(true, Ordering::SeqCst);
+    }
+    if let Some(handle) = join_handle {
+        let _ = handle.join();
+    }
 
     Ok(())
 }
 
-fn open_serial_port(
+pub(crate) fn open_serial_port(
     name: &String,
     settings: &SerialPortSettings,
 ) -> Result<Box<serialport::SerialPort>> {
@@ -161,18 +581,34 @@ fn open_serial_port(
     Ok(port)
 }
 
-fn protocols<P: AsRef<Path>>(path: P, cb: &Fn(&str) -> Result<()>) -> Result<()> {
+fn protocols<P: AsRef<Path>>(
+    path: P,
+    encoding: Option<Encoding>,
+    cb: &Fn(&[u8]) -> Result<()>,
+) -> Result<()> {
     let cb = |it: &DirEntry| -> Result<()> {
         let file = it.path();
         info!("load from file {}", file.display());
-        let fd = File::open(file)?;
-        let br = BufReader::new(fd);
-
-        for line in br.lines() {
-            let line = line?;
-            let line = line.trim();
-            if !line.is_empty() {
-                cb(line)?;
+        match Encoding::for_path(&file, encoding) {
+            Encoding::Raw => {
+                let mut buf = Vec::new();
+                File::open(&file)?.read_to_end(&mut buf)?;
+                cb(&buf)?;
+            }
+            encoding => {
+                let fd = File::open(&file)?;
+                let br = BufReader::new(fd);
+                for line in br.lines() {
+                    let line = line?;
+                    let line = line.trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    match encoding {
+                        Encoding::Hex => cb(&parse_hex_line(line)?)?,
+                        _ => cb(line.as_bytes())?,
+                    }
+                }
             }
         }
         Ok(())